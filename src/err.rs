@@ -3,4 +3,16 @@ pub enum GameError {
     BoardFull,
     AxisFull,
     TooFewDimensions,
+    WinLengthTooSmall,
+    /// `win_length` was greater than [`crate::MAX_WIN_LENGTH`], the largest
+    /// value the solver's heuristic can score without overflowing.
+    WinLengthTooLarge,
+    /// The given position didn't specify the coordinates in all but one
+    /// dimension.
+    InvalidPosition {
+        expected_dims: usize,
+        got_dims: usize,
+    },
+    /// One of the given coordinates lies outside the board.
+    IndexOutOfBounds,
 }
@@ -1,33 +1,86 @@
 mod err;
+mod solver;
+mod transposition;
 
 use err::GameError;
 use itertools::Itertools;
 use ndarray::prelude::*;
+use rand::Rng;
 use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
 
 const POSITION_CHANGES: [isize; 3] = [1, 0, -1];
 
+/// The largest `win_length` the solver's `10^n` heuristic can score without
+/// overflowing an `i64`, see [`GameState::with_win_length`].
+const MAX_WIN_LENGTH: usize = 18;
+
 fn main() {
     let mut game = GameState::new(&[7, 6]).unwrap();
     game.play_disk(Color::Red, &mut vec![5]).unwrap();
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct GameState {
     board: ArrayD<Option<Color>>,
     check_vecs: HashSet<Array1<isize>>,
     round: usize,
+    /// Random values used to incrementally compute `hash`, one per
+    /// `(flat_cell_index, Color)` pair. Shared (not regenerated) across
+    /// clones of the same game, so their hashes stay comparable.
+    zobrist_table: Rc<Vec<u64>>,
+    /// The Zobrist hash of the current position, see [`GameState::zobrist_hash`].
+    hash: u64,
+    /// How many disks in a row are needed to win, see [`GameState::with_win_length`].
+    win_length: usize,
+    /// The exact position of every disk played so far, in order, letting
+    /// `undo` unmake a move in place instead of requiring a full clone.
+    history: Vec<Vec<usize>>,
+}
+
+impl PartialEq for GameState {
+    fn eq(&self, other: &Self) -> bool {
+        self.board == other.board
+            && self.round == other.round
+            && self.win_length == other.win_length
+    }
 }
 
 impl GameState {
+    /// Create a new game with the classic Connect Four win condition of 4
+    /// disks in a row.
     pub fn new(dims: &[usize]) -> Result<Self, GameError> {
+        GameState::with_win_length(dims, 4)
+    }
+
+    /// Create a new "connect-k" game: `win_length` disks in a row are needed
+    /// to win instead of the classic 4, e.g. `5` for Gomoku-style play.
+    ///
+    /// `win_length` may be at most [`MAX_WIN_LENGTH`], the largest value the
+    /// solver's heuristic can score without overflowing; a larger value
+    /// returns `Err(GameError::WinLengthTooLarge)`.
+    pub fn with_win_length(dims: &[usize], win_length: usize) -> Result<Self, GameError> {
         if dims.len() < 2 {
             return Err(GameError::TooFewDimensions);
         }
+        if win_length < 2 {
+            return Err(GameError::WinLengthTooSmall);
+        }
+        if win_length > MAX_WIN_LENGTH {
+            return Err(GameError::WinLengthTooLarge);
+        }
+
+        let board: ArrayD<Option<Color>> = Array::from_elem(dims, None);
+        let zobrist_table = GameState::generate_zobrist_table(board.len());
 
         Ok(GameState {
-            board: Array::from_elem(dims, None),
             check_vecs: GameState::generate_check_vecs(dims.len()),
+            zobrist_table: Rc::new(zobrist_table),
+            hash: 0,
+            win_length,
+            history: Vec::new(),
+            board,
             round: 1,
         })
     }
@@ -39,7 +92,10 @@ impl GameState {
     /// The final position is the first free space along the unspecified axis.
     /// `pos` is updated to point exactly to the newly added disk.
     /// If there is no space left along this axis `Err(GameError::AxisFull)` will be returned.
-    /// 
+    /// `pos` is also validated before it is used: a wrong number of coordinates returns
+    /// `Err(GameError::InvalidPosition)` and an out-of-range coordinate returns
+    /// `Err(GameError::IndexOutOfBounds)`, instead of panicking.
+    ///
     /// Next this new position is checked for a winning row and the result returned.
     /// If the game has not been won, the round counter is incremented by one.
     pub fn play_disk(&mut self, color: Color, mut pos: &mut Vec<usize>) -> Result<bool, GameError> {
@@ -47,7 +103,7 @@ impl GameState {
             return Err(GameError::BoardFull);
         }
 
-        self.check_input(&pos);
+        self.check_input(&pos)?;
         self.insert_disk(color, &mut pos)?;
 
         let win = self.is_win_position(color, &pos);
@@ -68,15 +124,87 @@ impl GameState {
     fn insert_disk(&mut self, color: Color, pos: &mut Vec<usize>) -> Result<(), GameError> {
         let index = self.index_from_pos(&pos);
         let slice: ndarray::SliceInfo<_, IxDyn> = ndarray::SliceInfo::new(&index).unwrap();
-        let column = self.board.slice_mut(slice.as_ref());
 
-        if let Some((i, elem)) = column.into_iter().find_position(|elem| elem.is_none()) {
-            *elem = Some(color);
-            pos.push(i);
-            return Ok(());
+        let inserted_at = {
+            let column = self.board.slice_mut(slice.as_ref());
+            column
+                .into_iter()
+                .find_position(|elem| elem.is_none())
+                .map(|(i, elem)| {
+                    *elem = Some(color);
+                    i
+                })
+        };
+
+        match inserted_at {
+            Some(i) => {
+                pos.push(i);
+                self.update_hash(color, pos);
+                self.history.push(pos.clone());
+                Ok(())
+            }
+            None => Err(GameError::AxisFull),
+        }
+    }
+
+    /// Unmake the last move played, restoring the board, round counter, and
+    /// Zobrist hash to what they were before it.
+    ///
+    /// This lets a tree search reuse a single `GameState` in place instead of
+    /// cloning a new board for every move it explores. Returns `None`, and
+    /// leaves `self` unchanged, if no move has been played yet.
+    pub fn undo(&mut self) -> Option<()> {
+        let pos = self.history.pop()?;
+        let color = self.board[pos.as_slice()]?;
+        let was_win = self.is_win_position(color, &pos);
+
+        self.board[pos.as_slice()] = None;
+        self.update_hash(color, &pos);
+        if !was_win {
+            self.round -= 1;
         }
 
-        Err(GameError::AxisFull)
+        Some(())
+    }
+
+    /// The position of the last disk played, or `None` if the board is empty.
+    pub fn last_move(&self) -> Option<&[usize]> {
+        self.history.last().map(Vec::as_slice)
+    }
+
+    /// How many disks have been played so far.
+    pub fn move_count(&self) -> usize {
+        self.history.len()
+    }
+
+    /// The Zobrist hash of the current position.
+    ///
+    /// Incrementally updated in O(1) per move, so searchers can use it as a
+    /// cheap key into a `TranspositionTable` to detect positions that were
+    /// already searched via a different move order.
+    pub fn zobrist_hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// XOR the key for `(pos, color)` into the running Zobrist hash.
+    fn update_hash(&mut self, color: Color, pos: &[usize]) {
+        let flat = self.flat_index(pos);
+        self.hash ^= self.zobrist_table[flat * 2 + color.zobrist_index()];
+    }
+
+    /// The index `pos` would have in the board's underlying flat storage.
+    fn flat_index(&self, pos: &[usize]) -> usize {
+        self.board
+            .strides()
+            .iter()
+            .zip(pos)
+            .map(|(&stride, &p)| stride as usize * p)
+            .sum()
+    }
+
+    fn generate_zobrist_table(n_cells: usize) -> Vec<u64> {
+        let mut rng = rand::thread_rng();
+        (0..n_cells * 2).map(|_| rng.gen()).collect()
     }
 
     fn is_win_position(&self, color: Color, pos: &[usize]) -> bool {
@@ -89,7 +217,7 @@ impl GameState {
             // count the disks with `color` in the given `direction` and add them to the score
             score += self.check_direction(color, &mut checking, direction);
 
-            if score >= 4 {
+            if score >= self.win_length {
                 // checking in one direction can be enough
                 return true;
             }
@@ -97,7 +225,7 @@ impl GameState {
             // now do the same in the opposite direction
             checking = pos.to_owned();
             score += self.check_direction(color, &mut checking, &-direction);
-            if score >= 4 {
+            if score >= self.win_length {
                 return true;
             }
         }
@@ -113,7 +241,7 @@ impl GameState {
     ) -> usize {
         let mut score = 0;
 
-        for _ in 0..3 {
+        for _ in 0..self.win_length - 1 {
             *starting_pos += direction;
 
             let out_of_bounds = starting_pos
@@ -149,12 +277,78 @@ impl GameState {
         self.current_round() - 1
     }
 
-    fn check_input(&self, pos: &[usize]) {
+    pub const fn win_length(&self) -> usize {
+        self.win_length
+    }
+
+    /// The disk at `coords`, without exposing the underlying `ndarray` type.
+    ///
+    /// Returns `Some(None)` for an empty cell and `None` if `coords` is out
+    /// of range, so the two cases can be told apart.
+    pub fn get(&self, coords: &[usize]) -> Option<Option<Color>> {
+        if coords.len() != self.board.ndim()
+            || coords.iter().zip(self.board.shape()).any(|(&c, &d)| c >= d)
+        {
+            return None;
+        }
+
+        Some(self.board[coords])
+    }
+
+    /// The size of the board along each axis.
+    pub fn dims(&self) -> &[usize] {
+        self.board.shape()
+    }
+
+    /// Report why a move at `pos` would or wouldn't be playable right now.
+    ///
+    /// `pos` specifies the exact index in all but the last dimension, same
+    /// as the `pos` argument to `play_disk`.
+    pub fn move_status(&self, pos: &[usize]) -> MoveStatus {
         if pos.len() != self.board.ndim() - 1 {
-            panic!("The input position has to specify the coordinates in {} dimensions, but {} were given",
-                self.board.ndim() - 1,
-                pos.len()
-            );
+            return MoveStatus::WrongArity;
+        }
+
+        if pos.iter().zip(self.board.shape()).any(|(&p, &d)| p >= d) {
+            return MoveStatus::OutOfBounds;
+        }
+
+        if self.is_column_full(pos) {
+            MoveStatus::AxisFull
+        } else {
+            MoveStatus::Playable
+        }
+    }
+
+    /// Every column, given as the indices of all but the last axis, that
+    /// still has room for at least one more disk.
+    pub fn legal_moves(&self) -> Vec<Vec<usize>> {
+        let dims = self.board.shape();
+        let outer_dims = &dims[..dims.len() - 1];
+
+        outer_dims
+            .iter()
+            .map(|&d| 0..d)
+            .multi_cartesian_product()
+            .filter(|pos| self.move_status(pos) == MoveStatus::Playable)
+            .collect()
+    }
+
+    fn is_column_full(&self, pos: &[usize]) -> bool {
+        let index = self.index_from_pos(pos);
+        let slice: ndarray::SliceInfo<_, IxDyn> = ndarray::SliceInfo::new(&index).unwrap();
+        self.board.slice(slice.as_ref()).iter().all(Option::is_some)
+    }
+
+    /// Validate `pos` before it is used to insert a disk.
+    fn check_input(&self, pos: &[usize]) -> Result<(), GameError> {
+        match self.move_status(pos) {
+            MoveStatus::WrongArity => Err(GameError::InvalidPosition {
+                expected_dims: self.board.ndim() - 1,
+                got_dims: pos.len(),
+            }),
+            MoveStatus::OutOfBounds => Err(GameError::IndexOutOfBounds),
+            MoveStatus::AxisFull | MoveStatus::Playable => Ok(()),
         }
     }
 
@@ -200,15 +394,79 @@ impl GameState {
     }
 }
 
+impl fmt::Display for GameState {
+    /// Render a 2-dimensional board as rows of `.`/`R`/`Y`, with the first
+    /// row printed being the highest one. Boards of other dimensionalities
+    /// can't be rendered this way, so a message is printed instead.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.board.ndim() != 2 {
+            return write!(
+                f,
+                "<GameState can't be displayed: board has {} dimensions, not 2>",
+                self.board.ndim()
+            );
+        }
+
+        let (width, height) = (self.dims()[0], self.dims()[1]);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let c = match self.get(&[x, y]).flatten() {
+                    Some(Color::Red) => 'R',
+                    Some(Color::Yellow) => 'Y',
+                    None => '.',
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Why a move at a given position is, or isn't, currently playable.
+///
+/// Returned by `GameState::move_status` so callers can tell these cases
+/// apart without having to catch a panic or guess from a `GameError`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MoveStatus {
+    /// The move can be played right now.
+    Playable,
+    /// The axis has no room left for another disk.
+    AxisFull,
+    /// One of the coordinates lies outside the board.
+    OutOfBounds,
+    /// The position didn't specify the coordinates in all but one dimension.
+    WrongArity,
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Color {
     Red,
     Yellow,
 }
 
+impl Color {
+    /// The color of the other player.
+    pub const fn opponent(self) -> Color {
+        match self {
+            Color::Red => Color::Yellow,
+            Color::Yellow => Color::Red,
+        }
+    }
+
+    /// A stable 0/1 index used to look up this color's Zobrist key.
+    const fn zobrist_index(self) -> usize {
+        match self {
+            Color::Red => 0,
+            Color::Yellow => 1,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Color, GameError, GameState};
+    use super::{Color, GameError, GameState, MoveStatus};
 
     const N_DIMS: usize = 10;
     const DIMS: [usize; N_DIMS] = [3; N_DIMS];
@@ -226,6 +484,189 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_distinguishes_empty_cells_from_out_of_range_ones() {
+        let mut game = GameState::new(&[3, 2]).unwrap();
+        assert_eq!(game.get(&[0, 0]), Some(None));
+        assert_eq!(game.get(&[3, 0]), None);
+        assert_eq!(game.get(&[0]), None);
+
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        assert_eq!(game.get(&[0, 0]), Some(Some(Color::Red)));
+    }
+
+    #[test]
+    fn dims_reports_the_board_shape() {
+        let game = GameState::new(&[7, 6]).unwrap();
+        assert_eq!(game.dims(), &[7, 6]);
+    }
+
+    #[test]
+    fn display_renders_a_2d_board_top_row_first() {
+        let mut game = GameState::new(&[3, 2]).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![0]).unwrap();
+
+        assert_eq!(game.to_string(), "Y..\nR..\n");
+    }
+
+    #[test]
+    fn display_refuses_boards_with_more_than_two_dimensions() {
+        let game = GameState::new(&[3, 3, 3]).unwrap();
+        assert!(game.to_string().contains('3'));
+    }
+
+    #[test]
+    fn undo_restores_the_board_round_and_hash() {
+        let mut game = GameState::new(&[7, 6]).unwrap();
+        let empty = game.clone();
+
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        assert_eq!(game.last_move(), Some(&[0, 0][..]));
+        assert_eq!(game.move_count(), 1);
+
+        game.play_disk(Color::Yellow, &mut vec![0]).unwrap();
+        assert_eq!(game.move_count(), 2);
+
+        assert_eq!(game.undo(), Some(()));
+        assert_eq!(game.move_count(), 1);
+        assert_eq!(game.last_move(), Some(&[0, 0][..]));
+
+        assert_eq!(game.undo(), Some(()));
+        assert_eq!(game.move_count(), 0);
+        assert_eq!(game.last_move(), None);
+        assert_eq!(game, empty);
+        assert_eq!(game.zobrist_hash(), empty.zobrist_hash());
+    }
+
+    #[test]
+    fn undo_on_an_empty_board_returns_none() {
+        let mut game = GameState::new(&[7, 6]).unwrap();
+        assert_eq!(game.undo(), None);
+    }
+
+    #[test]
+    fn undo_after_a_win_does_not_touch_the_round_counter() {
+        let mut game = GameState::with_win_length(&[7, 6], 3).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        let round_before_win = game.current_round();
+
+        assert_eq!(game.play_disk(Color::Red, &mut vec![0]), Ok(true));
+        assert_eq!(game.current_round(), round_before_win);
+
+        game.undo().unwrap();
+        assert_eq!(game.current_round(), round_before_win);
+    }
+
+    #[test]
+    fn play_disk_returns_an_error_instead_of_panicking_on_bad_input() {
+        let mut game = GameState::new(&[7, 6]).unwrap();
+        assert_eq!(
+            game.play_disk(Color::Red, &mut vec![0, 0]),
+            Err(GameError::InvalidPosition {
+                expected_dims: 1,
+                got_dims: 2,
+            })
+        );
+        assert_eq!(
+            game.play_disk(Color::Red, &mut vec![7]),
+            Err(GameError::IndexOutOfBounds)
+        );
+    }
+
+    #[test]
+    fn move_status_reports_why_a_move_is_or_isnt_playable() {
+        let mut game = GameState::new(&[3, 2]).unwrap();
+        assert_eq!(game.move_status(&[0]), MoveStatus::Playable);
+        assert_eq!(game.move_status(&[0, 0]), MoveStatus::WrongArity);
+        assert_eq!(game.move_status(&[3]), MoveStatus::OutOfBounds);
+
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![0]).unwrap();
+        assert_eq!(game.move_status(&[0]), MoveStatus::AxisFull);
+    }
+
+    #[test]
+    fn legal_moves_lists_every_column_with_room_left() {
+        let mut game = GameState::new(&[3, 2]).unwrap();
+        assert_eq!(game.legal_moves(), vec![vec![0], vec![1], vec![2]]);
+
+        game.play_disk(Color::Red, &mut vec![1]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        assert_eq!(game.legal_moves(), vec![vec![0], vec![2]]);
+    }
+
+    #[test]
+    fn with_win_length_rejects_lengths_below_two() {
+        assert_eq!(
+            GameState::with_win_length(&[7, 6], 1),
+            Err(GameError::WinLengthTooSmall)
+        );
+        assert_eq!(
+            GameState::with_win_length(&[7, 6], 0),
+            Err(GameError::WinLengthTooSmall)
+        );
+    }
+
+    #[test]
+    fn with_win_length_rejects_lengths_above_the_solver_heuristics_limit() {
+        assert_eq!(
+            GameState::with_win_length(&[25, 25], 19),
+            Err(GameError::WinLengthTooLarge)
+        );
+        assert!(GameState::with_win_length(&[25, 25], 18).is_ok());
+    }
+
+    #[test]
+    fn connect_three_wins_with_three_in_a_row() {
+        let mut game = GameState::with_win_length(&[7, 6], 3).unwrap();
+        assert_eq!(game.win_length(), 3);
+
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        assert_eq!(game.play_disk(Color::Red, &mut vec![0]), Ok(true));
+    }
+
+    #[test]
+    fn connect_five_is_not_won_by_four_in_a_row() {
+        let mut game = GameState::with_win_length(&[7, 6], 5).unwrap();
+
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        assert_eq!(game.play_disk(Color::Red, &mut vec![0]), Ok(false));
+    }
+
+    #[test]
+    fn zobrist_hash_changes_with_every_move_and_is_reproducible() {
+        let mut game = GameState::new(&[7, 6]).unwrap();
+        let empty_hash = game.zobrist_hash();
+
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        let after_one_move = game.zobrist_hash();
+        assert_ne!(empty_hash, after_one_move);
+
+        game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        assert_ne!(after_one_move, game.zobrist_hash());
+
+        // replaying the same moves against the same table reproduces the hash
+        let mut replay = GameState {
+            zobrist_table: game.zobrist_table.clone(),
+            ..GameState::new(&[7, 6]).unwrap()
+        };
+        replay.play_disk(Color::Red, &mut vec![0]).unwrap();
+        replay.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        assert_eq!(replay.zobrist_hash(), game.zobrist_hash());
+    }
+
     #[test]
     fn d2_board_full() {
         let mut game = GameState::new(&DIMS[0..2]).unwrap();
@@ -0,0 +1,498 @@
+//! Tree search for finding good moves in a [`GameState`].
+
+use crate::transposition::{NodeType, TTEntry, TranspositionTable};
+use crate::{Color, GameState};
+use itertools::Itertools;
+use ndarray::prelude::*;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+
+/// Score assigned to a won position, minus the number of plies from the root
+/// it took to reach it, so that faster wins are preferred over slower ones.
+const WIN_SCORE: i64 = 1_000_000;
+
+/// Exploration constant used by [`GameState::mcts_move`]'s UCT formula.
+const UCT_C: f64 = std::f64::consts::SQRT_2;
+
+impl GameState {
+    /// Search the game tree `depth + 1` plies deep using negamax with
+    /// alpha-beta pruning and return the column that is best for `color` to
+    /// play. A `depth` of 0 still tries every move at the root once and
+    /// scores each one that doesn't win with the heuristic.
+    ///
+    /// Returns `None` if the board has no legal moves left.
+    pub fn best_move(&mut self, color: Color, depth: usize) -> Option<Vec<usize>> {
+        let mut tt = TranspositionTable::new();
+        self.negamax(color, depth, depth, -WIN_SCORE, WIN_SCORE, &mut tt)
+            .1
+    }
+
+    /// The negamax search itself: `color` is the player to move at `self`,
+    /// the returned score is always from `color`'s point of view, and the
+    /// returned move is the column that achieves it (`None` on a full
+    /// board). `tt` caches results across positions reached by different
+    /// move orders, enabling cutoffs from previously-computed bounds.
+    /// `depth` is the remaining search budget and shrinks towards 0 as the
+    /// recursion goes deeper, while `initial_depth` stays fixed at the
+    /// budget `best_move` was called with, so a win's score can be based on
+    /// how many plies from the root it took to reach it.
+    ///
+    /// Rather than cloning a new board per explored node, this plays each
+    /// candidate move on `self` with [`GameState::play_disk`] and unmakes it
+    /// with [`GameState::undo`] before trying the next one, so `self` is left
+    /// exactly as it was found once the search returns.
+    fn negamax(
+        &mut self,
+        color: Color,
+        depth: usize,
+        initial_depth: usize,
+        mut alpha: i64,
+        mut beta: i64,
+        tt: &mut TranspositionTable,
+    ) -> (i64, Option<Vec<usize>>) {
+        let hash = self.zobrist_hash();
+        let original_alpha = alpha;
+
+        if let Some(entry) = tt.get(hash) {
+            if entry.depth >= depth {
+                match entry.node_type {
+                    NodeType::Exact => return (entry.value, entry.best_move.clone()),
+                    NodeType::LowerBound => alpha = alpha.max(entry.value),
+                    NodeType::UpperBound => beta = beta.min(entry.value),
+                }
+                if alpha >= beta {
+                    return (entry.value, entry.best_move.clone());
+                }
+            }
+        }
+
+        let moves = self.legal_moves();
+        if moves.is_empty() {
+            // full board, nobody could win from here
+            return (0, None);
+        }
+
+        let mut value = -WIN_SCORE;
+        let mut best = None;
+
+        for pos in moves {
+            let mut played = pos.clone();
+            let won = self
+                .play_disk(color, &mut played)
+                .expect("pos came from legal_moves and is therefore playable");
+
+            let score = if won {
+                WIN_SCORE - (initial_depth - depth) as i64
+            } else if depth == 0 {
+                self.heuristic(color)
+            } else {
+                -self
+                    .negamax(
+                        color.opponent(),
+                        depth - 1,
+                        initial_depth,
+                        -beta,
+                        -alpha,
+                        tt,
+                    )
+                    .0
+            };
+
+            self.undo();
+
+            if score > value {
+                value = score;
+                best = Some(pos);
+            }
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let node_type = if value <= original_alpha {
+            NodeType::UpperBound
+        } else if value >= beta {
+            NodeType::LowerBound
+        } else {
+            NodeType::Exact
+        };
+        tt.insert(
+            hash,
+            TTEntry {
+                depth,
+                value,
+                node_type,
+                best_move: best.clone(),
+            },
+        );
+
+        (value, best)
+    }
+
+    /// Estimate how favorable the current position is for `color` by summing
+    /// the value of every still-winnable `win_length`-sized window on the
+    /// board.
+    ///
+    /// A window that already contains disks of both colors can never be won
+    /// by anyone and scores 0. Otherwise the window scores `10^n` for the
+    /// owning color, where `n` is the number of disks of that color already
+    /// in it. The heuristic is the sum of `color`'s window scores minus the
+    /// opponent's.
+    fn heuristic(&self, color: Color) -> i64 {
+        let dims = self.board.shape();
+        let mut score: i64 = 0;
+
+        for start in dims.iter().map(|&d| 0..d).multi_cartesian_product() {
+            let start = Array1::from(start).map(|&i| i as isize);
+
+            for direction in &self.check_vecs {
+                let end = &start + &(direction * (self.win_length() - 1) as isize);
+                let in_bounds = end
+                    .indexed_iter()
+                    .all(|(i, &ind)| ind >= 0 && (ind as usize) < dims[i]);
+                if !in_bounds {
+                    continue;
+                }
+
+                let mut red = 0;
+                let mut yellow = 0;
+                for step in 0..self.win_length() as isize {
+                    let pos = (&start + &(direction * step)).map(|&i| i as usize);
+                    match self.board[pos.as_slice().unwrap()] {
+                        Some(Color::Red) => red += 1,
+                        Some(Color::Yellow) => yellow += 1,
+                        None => {}
+                    }
+                }
+
+                if red > 0 && yellow > 0 {
+                    continue;
+                }
+
+                let (ours, theirs) = if color == Color::Red {
+                    (red, yellow)
+                } else {
+                    (yellow, red)
+                };
+
+                if ours > 0 {
+                    score = score.saturating_add(10i64.pow(ours));
+                } else if theirs > 0 {
+                    score = score.saturating_sub(10i64.pow(theirs));
+                }
+            }
+        }
+
+        score
+    }
+
+    /// Search for a move using Monte-Carlo tree search.
+    ///
+    /// This explores the game tree by repeatedly playing random games out to
+    /// completion and keeping statistics on which moves tend to lead to wins,
+    /// which copes far better than a fixed-depth [`GameState::best_move`]
+    /// search once the branching factor of the board gets large. `color` is
+    /// the player to move, and `iterations` bounds how many playouts are run.
+    ///
+    /// Returns the most-visited move at the root once the budget is spent.
+    ///
+    /// Rather than cloning a new board per node of the search tree, each
+    /// iteration plays its way down to the node it explores with
+    /// [`GameState::play_disk`] and unmakes every one of those moves with
+    /// [`GameState::undo`] before the next iteration starts, so `self` is
+    /// left exactly as it was found once the budget is spent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no legal moves left to make.
+    pub fn mcts_move(&mut self, color: Color, iterations: usize) -> Vec<usize> {
+        let mut nodes = vec![MctsNode::new(self.legal_moves(), color, None, None)];
+
+        for _ in 0..iterations {
+            let mut node = 0;
+            let mut plies = 0;
+
+            // 1. selection: descend while fully expanded and non-terminal
+            while nodes[node].untried.is_empty() && !nodes[node].children.is_empty() {
+                node = select_child(&nodes, node);
+                let mut pos = nodes[node].mv.clone().unwrap();
+                self.play_disk(nodes[node].mover(), &mut pos)
+                    .expect("moves recorded in the tree are always playable");
+                plies += 1;
+            }
+
+            // 2. expansion
+            if nodes[node].outcome.is_none() && !nodes[node].untried.is_empty() {
+                node = expand(&mut nodes, node, self);
+                plies += 1;
+            }
+
+            // 3. simulation
+            let winner = match nodes[node].outcome {
+                Some(winner) => winner,
+                None => {
+                    let (winner, rollout_plies) = rollout(self, nodes[node].to_move);
+                    plies += rollout_plies;
+                    winner
+                }
+            };
+
+            // 4. backpropagation
+            let mut current = Some(node);
+            while let Some(n) = current {
+                nodes[n].visits += 1;
+                if Some(nodes[n].mover()) == winner {
+                    nodes[n].wins += 1.0;
+                }
+                current = nodes[n].parent;
+            }
+
+            // unwind back to the root for the next iteration
+            for _ in 0..plies {
+                self.undo();
+            }
+        }
+
+        nodes[0]
+            .children
+            .iter()
+            .max_by_key(|&&child| nodes[child].visits)
+            .and_then(|&child| nodes[child].mv.clone())
+            .expect("mcts_move requires at least one legal move")
+    }
+}
+
+/// A node in the search tree built up by [`GameState::mcts_move`].
+///
+/// Each node holds the move that reached it from its parent, plus the
+/// visit/win statistics used to guide the next selection step. The board
+/// state a node represents isn't stored here: the search instead replays
+/// moves on a single shared `GameState` as it walks the tree.
+struct MctsNode {
+    /// The player to move from this state.
+    to_move: Color,
+    mv: Option<Vec<usize>>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Vec<usize>>,
+    visits: u32,
+    wins: f64,
+    /// `Some(winner)` once this state is known to be game over, where
+    /// `winner` is `None` for a draw.
+    outcome: Option<Option<Color>>,
+}
+
+impl MctsNode {
+    fn new(
+        untried: Vec<Vec<usize>>,
+        to_move: Color,
+        mv: Option<Vec<usize>>,
+        parent: Option<usize>,
+    ) -> Self {
+        MctsNode {
+            to_move,
+            mv,
+            parent,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            wins: 0.0,
+            outcome: None,
+        }
+    }
+
+    /// The player whose move led to this node.
+    fn mover(&self) -> Color {
+        self.to_move.opponent()
+    }
+
+    fn uct(&self, parent_visits: f64) -> f64 {
+        let visits = f64::from(self.visits);
+        let win_rate = self.wins / visits;
+        win_rate + UCT_C * (parent_visits.ln() / visits).sqrt()
+    }
+}
+
+/// Pick the child maximizing the UCT score.
+fn select_child(nodes: &[MctsNode], parent: usize) -> usize {
+    let parent_visits = f64::from(nodes[parent].visits);
+
+    nodes[parent]
+        .children
+        .iter()
+        .copied()
+        .max_by(|&a, &b| {
+            nodes[a]
+                .uct(parent_visits)
+                .partial_cmp(&nodes[b].uct(parent_visits))
+                .unwrap()
+        })
+        .expect("select_child only runs on nodes that have children")
+}
+
+/// Play one untried move at `parent` on `state`, adding the resulting
+/// position as a new child node, and return that child's index.
+///
+/// `state` is left positioned at the new child, as if `GameState::play_disk`
+/// had just been called on it by the caller.
+fn expand(nodes: &mut Vec<MctsNode>, parent: usize, state: &mut GameState) -> usize {
+    let mv = nodes[parent].untried.pop().unwrap();
+    let mover = nodes[parent].to_move;
+
+    let mut pos = mv.clone();
+    let won = state
+        .play_disk(mover, &mut pos)
+        .expect("mv came from legal_moves and is therefore playable");
+
+    let untried = state.legal_moves();
+    let mut child = MctsNode::new(untried, mover.opponent(), Some(mv), Some(parent));
+    if won {
+        child.outcome = Some(Some(mover));
+    } else if child.untried.is_empty() {
+        child.outcome = Some(None);
+    }
+
+    nodes.push(child);
+    let index = nodes.len() - 1;
+    nodes[parent].children.push(index);
+    index
+}
+
+/// Play uniformly random legal moves on `state` until the game ends,
+/// returning the winner (`None` for a draw) and the number of moves played,
+/// so the caller can unmake them with `GameState::undo`.
+fn rollout(state: &mut GameState, mut to_move: Color) -> (Option<Color>, usize) {
+    let mut rng = thread_rng();
+    let mut plies = 0;
+
+    loop {
+        let moves = state.legal_moves();
+        let mv = match moves.choose(&mut rng) {
+            Some(mv) => mv.clone(),
+            None => return (None, plies),
+        };
+
+        let mut pos = mv;
+        let won = state
+            .play_disk(to_move, &mut pos)
+            .expect("mv came from legal_moves and is therefore playable");
+        plies += 1;
+        if won {
+            return (Some(to_move), plies);
+        }
+
+        to_move = to_move.opponent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameState, TranspositionTable, WIN_SCORE};
+    use crate::Color;
+
+    #[test]
+    fn an_immediate_win_scores_higher_than_the_same_win_found_deeper_in_the_tree() {
+        // Two identical positions, one move away from a win: negamax should
+        // score the win higher when it costs fewer plies from the root
+        // (a smaller `initial_depth - depth`), never the other way around.
+        let mut game = GameState::new(&[4, 4]).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+        game.play_disk(Color::Red, &mut vec![0]).unwrap();
+
+        let mut tt = TranspositionTable::new();
+        let (found_at_root, _) = game.negamax(Color::Red, 3, 3, -WIN_SCORE, WIN_SCORE, &mut tt);
+
+        let mut tt = TranspositionTable::new();
+        let (found_three_plies_deep, _) =
+            game.negamax(Color::Red, 0, 3, -WIN_SCORE, WIN_SCORE, &mut tt);
+
+        assert!(found_at_root > found_three_plies_deep);
+    }
+
+    #[test]
+    fn heuristic_windows_scale_with_the_configured_win_length() {
+        let mut four = GameState::new(&[6, 6]).unwrap();
+        let mut five = GameState::with_win_length(&[6, 6], 5).unwrap();
+        for game in [&mut four, &mut five] {
+            game.play_disk(Color::Red, &mut vec![0]).unwrap();
+            game.play_disk(Color::Red, &mut vec![1]).unwrap();
+            game.play_disk(Color::Red, &mut vec![2]).unwrap();
+        }
+
+        // The same 3-in-a-row is one disk short of completing a window under
+        // win_length 4, but two short under win_length 5, so a heuristic
+        // that actually looks at `win_length` must score them differently.
+        assert_ne!(four.heuristic(Color::Red), five.heuristic(Color::Red));
+    }
+
+    #[test]
+    fn heuristic_score_saturates_instead_of_overflowing_on_a_dense_board() {
+        // At win_length 18 a single fully-owned window already scores
+        // 10^18, and a board this size has dozens of them (every row,
+        // every column, and both full diagonals) — summing them naively
+        // would overflow an i64, so the accumulator must saturate instead.
+        let mut game = GameState::with_win_length(&[18, 18], 18).unwrap();
+        for cell in game.board.iter_mut() {
+            *cell = Some(Color::Red);
+        }
+
+        assert_eq!(game.heuristic(Color::Red), i64::MAX);
+    }
+
+    #[test]
+    fn takes_the_immediate_win() {
+        let mut game = GameState::new(&[7, 6]).unwrap();
+        for _ in 0..3 {
+            game.play_disk(Color::Red, &mut vec![0]).unwrap();
+            game.play_disk(Color::Yellow, &mut vec![1]).unwrap();
+        }
+
+        let mv = game.best_move(Color::Red, 3).unwrap();
+        assert_eq!(mv, vec![0]);
+    }
+
+    #[test]
+    fn blocks_the_opponents_win() {
+        let mut game = GameState::new(&[7, 6]).unwrap();
+        for col in [1, 3, 5] {
+            game.play_disk(Color::Yellow, &mut vec![0]).unwrap();
+            game.play_disk(Color::Red, &mut vec![col]).unwrap();
+        }
+
+        let mv = game.best_move(Color::Red, 3).unwrap();
+        assert_eq!(mv, vec![0]);
+    }
+
+    #[test]
+    fn returns_none_on_a_full_board() {
+        let mut game = GameState::new(&[2, 2]).unwrap();
+        for i in 0..game.max_rounds() {
+            game.play_disk(Color::Yellow, &mut vec![i % 2]).unwrap();
+        }
+
+        assert_eq!(game.best_move(Color::Red, 2), None);
+    }
+
+    #[test]
+    fn mcts_blocks_the_opponents_win() {
+        let mut game = GameState::new(&[4, 4]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![0]).unwrap();
+        game.play_disk(Color::Red, &mut vec![1]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![0]).unwrap();
+        game.play_disk(Color::Red, &mut vec![2]).unwrap();
+        game.play_disk(Color::Yellow, &mut vec![0]).unwrap();
+
+        let mv = game.mcts_move(Color::Red, 2000);
+        assert_eq!(mv, vec![0]);
+    }
+
+    #[test]
+    fn mcts_picks_a_legal_move_on_a_fresh_board() {
+        let mut game = GameState::new(&[3, 3]).unwrap();
+        let mv = game.mcts_move(Color::Red, 50);
+        assert_eq!(mv.len(), 1);
+        assert!(mv[0] < 3);
+    }
+}
@@ -0,0 +1,59 @@
+//! A transposition table for caching search results by position.
+
+use std::collections::HashMap;
+
+/// What kind of bound a cached [`TTEntry`] represents.
+///
+/// Alpha-beta search doesn't always learn the exact value of a position: a
+/// beta cutoff only proves the value is at least `value` (`LowerBound`), and
+/// a node where no move improved alpha only proves it is at most `value`
+/// (`UpperBound`). Only a node searched without being cut off is `Exact`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeType {
+    Exact,
+    LowerBound,
+    UpperBound,
+}
+
+/// A cached search result for one position, keyed by its Zobrist hash in a
+/// [`TranspositionTable`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TTEntry {
+    pub depth: usize,
+    pub value: i64,
+    pub node_type: NodeType,
+    pub best_move: Option<Vec<usize>>,
+}
+
+/// Caches negamax search results by `GameState::zobrist_hash`, so a position
+/// reachable by more than one move order is only searched once.
+#[derive(Debug, Default)]
+pub struct TranspositionTable {
+    entries: HashMap<u64, TTEntry>,
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        TranspositionTable::default()
+    }
+
+    /// The cached entry for `hash`, if there is one.
+    pub fn get(&self, hash: u64) -> Option<&TTEntry> {
+        self.entries.get(&hash)
+    }
+
+    /// Cache `entry` for `hash`.
+    ///
+    /// If an entry is already stored for this hash, it is only replaced when
+    /// `entry` was searched to at least as great a depth, so a deep result
+    /// is never evicted by a shallower one.
+    pub fn insert(&mut self, hash: u64, entry: TTEntry) {
+        if self
+            .entries
+            .get(&hash)
+            .is_none_or(|existing| entry.depth >= existing.depth)
+        {
+            self.entries.insert(hash, entry);
+        }
+    }
+}